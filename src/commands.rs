@@ -0,0 +1,126 @@
+// Actuation commands that mutate PulseAudio state (mute, volume, default source). Each handler
+// follows the same lock/unlock + mpsc success-channel idiom as `connect_to_server`: lock the
+// mainloop, fire the introspection operation, wait for its callback, then unlock.
+
+use std::error::Error;
+use std::sync::mpsc;
+
+use log::debug;
+use pulse::volume::{ChannelVolumes, Volume};
+
+use crate::{RContext, RMainloop};
+
+pub fn toggle_source_mute(
+    mainloop: &RMainloop,
+    context: &RContext,
+    idx: u32,
+    mute: bool,
+) -> Result<(), Box<dyn Error>> {
+    mainloop.borrow_mut().lock();
+
+    let (tx, rx) = mpsc::channel();
+    context
+        .borrow_mut()
+        .introspect()
+        .set_source_mute_by_index(idx, mute, Some(Box::new(move |success| {
+            let _ = tx.send(success);
+        })));
+
+    mainloop.borrow_mut().unlock();
+
+    if rx.recv()? {
+        debug!("Set source {} mute to {}", idx, mute);
+        Ok(())
+    } else {
+        Err(format!("Failed to set mute for source {}", idx).into())
+    }
+}
+
+// Normalize `pct` against `base_volume` rather than `Volume::NORMAL`, matching
+// `SourceDatum::volume_pct` so a `SOURCE VOLUME N%` read and a `set-volume N` write agree on
+// hardware whose 0dB point isn't `NORMAL` (e.g. a boosted max).
+fn target_volume(base_volume: Volume, pct: u32) -> Volume {
+    Volume((base_volume.0 as f64 * pct as f64 / 100.0) as u32)
+}
+
+pub fn set_source_volume(
+    mainloop: &RMainloop,
+    context: &RContext,
+    idx: u32,
+    current_volume: &ChannelVolumes,
+    base_volume: Volume,
+    pct: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut volume = *current_volume;
+    volume.set(current_volume.len(), target_volume(base_volume, pct));
+
+    mainloop.borrow_mut().lock();
+
+    let (tx, rx) = mpsc::channel();
+    context
+        .borrow_mut()
+        .introspect()
+        .set_source_volume_by_index(idx, &volume, Some(Box::new(move |success| {
+            let _ = tx.send(success);
+        })));
+
+    mainloop.borrow_mut().unlock();
+
+    if rx.recv()? {
+        debug!("Set source {} volume to {}%", idx, pct);
+        Ok(())
+    } else {
+        Err(format!("Failed to set volume for source {}", idx).into())
+    }
+}
+
+pub fn set_default_source(
+    mainloop: &RMainloop,
+    context: &RContext,
+    name: &str,
+) -> Result<(), Box<dyn Error>> {
+    mainloop.borrow_mut().lock();
+
+    let (tx, rx) = mpsc::channel();
+    context
+        .borrow_mut()
+        .set_default_source(name, move |success| {
+            let _ = tx.send(success);
+        });
+
+    mainloop.borrow_mut().unlock();
+
+    if rx.recv()? {
+        debug!("Set default source to '{}'", name);
+        Ok(())
+    } else {
+        Err(format!("Failed to set default source to '{}'", name).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_volume_at_base_is_normal() {
+        let base = Volume(65536);
+        assert_eq!(target_volume(base, 100).0, base.0);
+    }
+
+    #[test]
+    fn target_volume_scales_with_percentage() {
+        let base = Volume(65536);
+        assert_eq!(target_volume(base, 50).0, 32768);
+        assert_eq!(target_volume(base, 0).0, 0);
+    }
+
+    #[test]
+    fn target_volume_tracks_boosted_base_volume() {
+        // A device whose base_volume is above NORMAL (e.g. a boosted max) should scale from
+        // that reported base, not from Volume::NORMAL.
+        let base = Volume(98304); // 1.5x NORMAL
+        assert_eq!(target_volume(base, 100).0, 98304);
+        assert_eq!(target_volume(base, 50).0, 49152);
+    }
+}
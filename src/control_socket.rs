@@ -0,0 +1,363 @@
+// Control/query subsystem: lets other processes connect to a Unix domain socket, receive a
+// snapshot of the current listener state, then stream mute/volume events as newline-delimited
+// JSON frames as they happen.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use log::{debug, error};
+use serde::Serialize;
+
+use crate::StateChange;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceSnapshot {
+    pub name: String,
+    pub mute: bool,
+    pub volume_pct: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SinkSnapshot {
+    pub name: String,
+    pub mute: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StateSnapshot {
+    pub default_source: Option<SourceSnapshot>,
+    pub default_sink: Option<SinkSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SocketEvent {
+    Mute { source: String, muted: bool },
+    Volume { source: String, pct: i32 },
+    SinkMute { sink: String, muted: bool },
+}
+
+// Everything `broadcast_events` can be asked to write to a client stream. Keeping both a
+// fan-out broadcast and a reply-to-one-client send in the same enum/channel means that thread
+// is the *only* writer of any client `UnixStream` once the client is registered (the accept loop
+// writes the initial snapshot line before that), so a mute event and a command reply can never
+// interleave their bytes on the wire.
+pub enum OutgoingMessage {
+    Event(SocketEvent),
+    Reply { client_id: u64, message: String },
+}
+
+// A handle to the running control-socket subsystem, used to ask it to shut down cleanly. Also
+// shuts down on drop, so the socket still gets torn down if `subscribe_source_mute` returns
+// early through `?` instead of reaching its ordinary teardown path.
+pub struct Handle {
+    shutdown: Arc<AtomicBool>,
+    socket_path: PathBuf,
+}
+
+impl Handle {
+    // Signals the accept loop to stop and clean up, then unblocks its blocking `accept()` call
+    // by connecting to the socket itself (the connection is dropped once seen).
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        let _ = UnixStream::connect(&self.socket_path);
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// Spawns the accept loop and the event broadcaster on dedicated threads so they can coexist
+// with the blocking PulseAudio mainloop running on the main thread. Inbound commands are parsed
+// off of each client's reader thread, but forwarded to the main thread over `commands` for
+// execution, since the mainloop/context handles backing `StateChange` processing are not `Send`.
+pub fn spawn(
+    socket_path: PathBuf,
+    snapshot: Arc<Mutex<StateSnapshot>>,
+    outgoing_tx: mpsc::Sender<OutgoingMessage>,
+    outgoing_rx: mpsc::Receiver<OutgoingMessage>,
+    commands: mpsc::Sender<StateChange>,
+) -> Result<Handle, Box<dyn Error>> {
+    if socket_path.exists() {
+        std::fs::remove_file(&socket_path)?;
+    }
+    let listener = UnixListener::bind(&socket_path)?;
+    // The socket accepts actuation commands (mute/volume/default-source changes), so restrict it
+    // to the owning user the same way a private key or cookie file would be protected.
+    fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+    debug!("Control socket listening on {:?}", socket_path);
+
+    let clients: Arc<Mutex<HashMap<u64, UnixStream>>> = Arc::new(Mutex::new(HashMap::new()));
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    {
+        let clients = Arc::clone(&clients);
+        let shutdown = Arc::clone(&shutdown);
+        let socket_path = socket_path.clone();
+        let outgoing_tx = outgoing_tx.clone();
+        thread::spawn(move || {
+            accept_clients(listener, clients, snapshot, commands, outgoing_tx, shutdown, socket_path)
+        });
+    }
+
+    thread::spawn(move || broadcast_events(outgoing_rx, clients));
+
+    Ok(Handle { shutdown, socket_path })
+}
+
+fn accept_clients(
+    listener: UnixListener,
+    clients: Arc<Mutex<HashMap<u64, UnixStream>>>,
+    snapshot: Arc<Mutex<StateSnapshot>>,
+    commands: mpsc::Sender<StateChange>,
+    outgoing_tx: mpsc::Sender<OutgoingMessage>,
+    shutdown: Arc<AtomicBool>,
+    socket_path: PathBuf,
+) {
+    let mut next_client_id: u64 = 0;
+
+    for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            debug!("Control socket shutting down");
+            break;
+        }
+
+        match stream {
+            Ok(stream) => {
+                let client_id = next_client_id;
+                next_client_id += 1;
+
+                let line = {
+                    let snapshot = snapshot.lock().unwrap();
+                    serde_json::to_string(&*snapshot).unwrap()
+                };
+
+                let mut writer = match stream.try_clone() {
+                    Ok(writer) => writer,
+                    Err(err) => {
+                        error!("Failed to clone control socket stream: {}", err);
+                        continue;
+                    }
+                };
+                if let Err(err) = writeln!(writer, "{}", line) {
+                    debug!("New control socket client disconnected before snapshot write: {}", err);
+                    continue;
+                }
+                debug!("Control socket client {} connected", client_id);
+                clients.lock().unwrap().insert(client_id, writer);
+
+                let commands = commands.clone();
+                let outgoing_tx = outgoing_tx.clone();
+                thread::spawn(move || handle_client_commands(client_id, stream, commands, outgoing_tx));
+            }
+            Err(err) => error!("Failed to accept control socket client: {}", err),
+        }
+    }
+
+    debug!("Control socket accept loop exiting, closing client connections");
+    clients.lock().unwrap().clear();
+    let _ = fs::remove_file(&socket_path);
+}
+
+// Reads newline-delimited command verbs from one client and forwards each as a `StateChange` for
+// the main thread to act on. Replies (success or parse error) are routed through `outgoing_tx` so
+// `broadcast_events` remains the sole writer of this client's stream.
+fn handle_client_commands(
+    client_id: u64,
+    stream: UnixStream,
+    commands: mpsc::Sender<StateChange>,
+    outgoing_tx: mpsc::Sender<OutgoingMessage>,
+) {
+    let reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(err) => {
+            error!("Failed to clone control socket stream for reading: {}", err);
+            return;
+        }
+    };
+
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                debug!("Control socket read error: {}", err);
+                return;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match parse_command(line) {
+            Ok(command) => {
+                if commands.send(StateChange::Control(command, client_id)).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                let message = format!("{{\"ok\":false,\"error\":{:?}}}", err);
+                let _ = outgoing_tx.send(OutgoingMessage::Reply { client_id, message });
+            }
+        }
+    }
+}
+
+fn parse_command(line: &str) -> Result<crate::Command, String> {
+    let mut parts = line.split_whitespace();
+    match parts.next() {
+        Some("toggle-mute") => Ok(crate::Command::ToggleMute),
+        Some("set-volume") => {
+            let pct = parts
+                .next()
+                .ok_or("set-volume requires a percentage argument")?;
+            let pct: u32 = pct
+                .parse()
+                .map_err(|_| "set-volume percentage must be a non-negative integer".to_string())?;
+            Ok(crate::Command::SetVolume { pct })
+        }
+        Some("set-default") => {
+            let name = parts
+                .next()
+                .ok_or("set-default requires a source name argument")?;
+            Ok(crate::Command::SetDefault {
+                name: name.to_string(),
+            })
+        }
+        Some(other) => Err(format!("unknown command '{}'", other)),
+        None => Err("empty command".to_string()),
+    }
+}
+
+// The sole writer of every registered client `UnixStream`: fans event broadcasts out to all connected
+// clients, and routes command replies to the one client that sent the command.
+fn broadcast_events(events: mpsc::Receiver<OutgoingMessage>, clients: Arc<Mutex<HashMap<u64, UnixStream>>>) {
+    for message in events {
+        match message {
+            OutgoingMessage::Event(event) => {
+                let line = match serde_json::to_string(&event) {
+                    Ok(line) => line,
+                    Err(err) => {
+                        error!("Failed to serialize control socket event: {}", err);
+                        continue;
+                    }
+                };
+
+                let mut clients = clients.lock().unwrap();
+                clients.retain(|_, client| match writeln!(client, "{}", line) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        debug!("Dropping disconnected control socket client: {}", err);
+                        false
+                    }
+                });
+            }
+            OutgoingMessage::Reply { client_id, message } => {
+                let mut clients = clients.lock().unwrap();
+                if let Some(client) = clients.get_mut(&client_id) {
+                    if let Err(err) = writeln!(client, "{}", message) {
+                        debug!("Dropping disconnected control socket client: {}", err);
+                        clients.remove(&client_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_command_toggle_mute() {
+        assert!(matches!(parse_command("toggle-mute"), Ok(crate::Command::ToggleMute)));
+    }
+
+    #[test]
+    fn parse_command_set_volume_parses_percentage() {
+        match parse_command("set-volume 42") {
+            Ok(crate::Command::SetVolume { pct }) => assert_eq!(pct, 42),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_command_set_volume_requires_argument() {
+        assert!(parse_command("set-volume").is_err());
+    }
+
+    #[test]
+    fn parse_command_set_volume_rejects_non_integer() {
+        assert!(parse_command("set-volume not-a-number").is_err());
+    }
+
+    #[test]
+    fn parse_command_set_default_parses_name() {
+        match parse_command("set-default my-source") {
+            Ok(crate::Command::SetDefault { name }) => assert_eq!(name, "my-source"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_command_set_default_requires_name() {
+        assert!(parse_command("set-default").is_err());
+    }
+
+    #[test]
+    fn parse_command_rejects_unknown_verb() {
+        assert!(parse_command("frobnicate").is_err());
+    }
+
+    #[test]
+    fn parse_command_rejects_empty_line() {
+        assert!(parse_command("").is_err());
+    }
+
+    #[test]
+    fn broadcast_reply_routes_to_the_requesting_client_only() {
+        let (client_a, mut test_a) = UnixStream::pair().unwrap();
+        let (client_b, mut test_b) = UnixStream::pair().unwrap();
+
+        let mut clients = HashMap::new();
+        clients.insert(0u64, client_a);
+        clients.insert(1u64, client_b);
+        let clients = Arc::new(Mutex::new(clients));
+
+        let (tx, rx) = mpsc::channel();
+        let worker_clients = Arc::clone(&clients);
+        let worker = thread::spawn(move || broadcast_events(rx, worker_clients));
+
+        tx.send(OutgoingMessage::Reply {
+            client_id: 0,
+            message: "{\"ok\":true}".to_string(),
+        })
+        .unwrap();
+        drop(tx);
+        worker.join().unwrap();
+
+        test_a.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let mut line_a = String::new();
+        BufReader::new(&mut test_a).read_line(&mut line_a).unwrap();
+        assert_eq!(line_a.trim(), "{\"ok\":true}");
+
+        test_b.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let mut buf = [0u8; 1];
+        let err = test_b.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+    }
+}
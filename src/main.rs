@@ -1,8 +1,13 @@
-mod callbacks;
+mod commands;
+mod control_socket;
+mod output;
+mod shutdown;
 
 use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex};
 use std::{
     cell::RefCell,
     error::Error,
@@ -12,23 +17,33 @@ use std::{
 use chrono::Local;
 use std::io::Write;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use env_logger::Env;
 use log::{debug, error, info, trace};
 use pulse::{
     callbacks::ListResult,
     context::{
+        introspect::ServerInfo,
         subscribe::{Facility, InterestMaskSet, Operation},
         Context, FlagSet, State,
     },
     mainloop::threaded::Mainloop,
     proplist::Proplist,
+    volume::{ChannelVolumes, Volume},
 };
 
+use control_socket::{OutgoingMessage, SocketEvent, StateSnapshot};
+use output::{formatter_for, Formatter, OutputEvent, OutputFormat};
+
 type RContext = Rc<RefCell<Context>>;
 type RMainloop = Rc<RefCell<Mainloop>>;
 
 type Sources = HashMap<u32, SourceDatum>;
+type Sinks = HashMap<u32, SinkDatum>;
+
+// Minimum change in normalized volume percentage before we bother printing a VOLUME line, so
+// tiny adjustments (e.g. scroll-wheel jitter) don't spam the output.
+const VOLUME_CHANGE_THRESHOLD_PCT: i32 = 2;
 
 #[derive(Parser, Debug)]
 #[clap(author = "Sam Martin-Brown", version, about)]
@@ -38,21 +53,75 @@ struct Args {
     #[arg(short = 'v')]
     verbose: bool,
 
-    /// an optional name to greet
-    #[arg()]
-    name: Option<String>,
+    /// Unix domain socket path other processes can connect to for state and events.
+    /// Defaults to `$XDG_RUNTIME_DIR/pulseaudio-source-listener.sock`.
+    #[arg(long)]
+    socket_path: Option<PathBuf>,
+
+    /// Output format for printed state/events
+    #[arg(long, value_enum, default_value = "plain")]
+    format: OutputFormat,
+
+    /// Actuation command to run against the default source, instead of starting the listener
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Toggle mute on the current default source
+    ToggleMute,
+    /// Set the current default source's volume to a percentage of its base (0dB) volume
+    SetVolume { pct: u32 },
+    /// Switch the default source to the named device
+    SetDefault { name: String },
+}
+
+fn resolve_socket_path(args: &Args) -> PathBuf {
+    if let Some(path) = &args.socket_path {
+        return path.clone();
+    }
+
+    let runtime_dir = env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(runtime_dir).join("pulseaudio-source-listener.sock")
 }
 
 #[derive(Debug, Clone)]
 struct SourceDatum {
     name: String,
     mute: bool,
+    volume: ChannelVolumes,
+    base_volume: Volume,
 }
 impl SourceDatum {
-    fn new(name: String, mute: bool) -> Self {
+    fn new(name: String, mute: bool, volume: ChannelVolumes, base_volume: Volume) -> Self {
         SourceDatum {
             name: name.to_string(),
             mute,
+            volume,
+            base_volume,
+        }
+    }
+
+    // Average channel volume, normalized against the device's reported `base_volume` (its
+    // 0dB/"unity gain" point), as a percentage. Most devices report `base_volume == NORMAL`, but
+    // some (e.g. hardware with a boosted max) don't, so normalizing against `NORMAL` directly
+    // would under/overstate the percentage a volume widget should show.
+    fn volume_pct(&self) -> i32 {
+        (self.volume.avg().0 as f64 / self.base_volume.0 as f64 * 100.0).round() as i32
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SinkDatum {
+    name: String,
+    mute: bool,
+}
+impl SinkDatum {
+    fn new(name: String, mute: bool) -> Self {
+        SinkDatum {
+            name: name.to_string(),
+            mute,
         }
     }
 }
@@ -61,26 +130,38 @@ impl SourceDatum {
 struct ListenerState {
     // Use Pulseaudio's source index as key to source data (which is just name and mute-status)
     sources: Sources,
-    default_source: u32,
+    default_source: Option<u32>,
+    // Same idea, but for sinks (speakers/headphones) rather than sources (microphones)
+    sinks: Sinks,
+    default_sink: Option<u32>,
 }
 
 impl ListenerState {
     fn new(mainloop: &RMainloop, context: &RContext) -> Result<Self, Box<dyn Error>> {
         let sources = get_sources(context, mainloop)?;
         let default_source = get_default_source_index(mainloop, context, &sources)?;
+        let sinks = get_sinks(context, mainloop)?;
+        let default_sink = get_default_sink_index(mainloop, context, &sinks)?;
         Ok(Self {
             sources,
             default_source,
+            sinks,
+            default_sink,
         })
     }
 
-    fn default_source<'a>(&'a self) -> Option<&'a SourceDatum> {
-        self.sources.get(&self.default_source)
+    fn default_source(&self) -> Option<&SourceDatum> {
+        self.default_source.and_then(|idx| self.sources.get(&idx))
+    }
+
+    fn default_sink(&self) -> Option<&SinkDatum> {
+        self.default_sink.and_then(|idx| self.sinks.get(&idx))
     }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    setup_logs();
+    let args = Args::parse();
+    setup_logs(&args);
 
     let mainloop = Rc::new(RefCell::new(Mainloop::new().ok_or("mainloop new failed")?));
 
@@ -96,142 +177,319 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     debug!("We should be connected at this point..!");
 
     let state = ListenerState::new(&mainloop, &context)?;
-    subscribe_source_mute(mainloop, context, state)
+
+    if let Some(command) = args.command {
+        return run_command(command, &mainloop, &context, &state);
+    }
+
+    let formatter = formatter_for(args.format);
+    let initial_snapshot = snapshot_from_state(&state);
+    if let Some(line) = formatter.snapshot(&initial_snapshot) {
+        println!("{}", line);
+    }
+
+    let snapshot = Arc::new(Mutex::new(initial_snapshot));
+    let (event_tx, event_rx) = mpsc::channel();
+    let (tx, rx) = mpsc::channel();
+    let socket_path = resolve_socket_path(&args);
+    let control_socket_handle = control_socket::spawn(
+        socket_path,
+        Arc::clone(&snapshot),
+        event_tx.clone(),
+        event_rx,
+        tx.clone(),
+    )?;
+    shutdown::spawn(tx.clone())?;
+
+    subscribe_source_mute(
+        mainloop,
+        context,
+        state,
+        EventLoopCtx {
+            snapshot,
+            socket_tx: event_tx,
+            tx,
+            rx,
+            formatter,
+            control_socket_handle,
+        },
+    )
 }
 
-enum SrcListState {
-    // InProg,
-    Item(u32, SourceDatum),
+// Runs a one-shot actuation command (CLI subcommand or control-socket verb) against the current
+// default source. Reuses the lock/unlock/channel idiom from `connect_to_server` via the
+// `commands` module.
+fn run_command(
+    command: Command,
+    mainloop: &RMainloop,
+    context: &RContext,
+    state: &ListenerState,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::ToggleMute => {
+            let idx = state.default_source.ok_or("No default source to toggle mute on")?;
+            let mute = state.default_source().map(|src| src.mute).unwrap_or(false);
+            commands::toggle_source_mute(mainloop, context, idx, !mute)
+        }
+        Command::SetVolume { pct } => {
+            let idx = state.default_source.ok_or("No default source to set volume on")?;
+            let datum = state
+                .default_source()
+                .ok_or("No default source to set volume on")?;
+            commands::set_source_volume(mainloop, context, idx, &datum.volume, datum.base_volume, pct)
+        }
+        Command::SetDefault { name } => commands::set_default_source(mainloop, context, &name),
+    }
+}
+
+fn snapshot_from_state(state: &ListenerState) -> StateSnapshot {
+    StateSnapshot {
+        default_source: state.default_source().map(|src| control_socket::SourceSnapshot {
+            name: src.name.clone(),
+            mute: src.mute,
+            volume_pct: src.volume_pct(),
+        }),
+        default_sink: state.default_sink().map(|sink| control_socket::SinkSnapshot {
+            name: sink.name.clone(),
+            mute: sink.mute,
+        }),
+    }
+}
+
+// Shared result type for both the full-list and by-index introspection queries below: an
+// `Item` per entry the callback is handed, a sentinel `Done` when pulseaudio has no more to
+// send, and `Err` if the query itself failed.
+enum ListMsg<T> {
+    Item(u32, T),
     Done,
     Err(String),
 }
 
-fn get_sources(context: &RContext, mainloop: &RMainloop) -> Result<Sources, Box<dyn Error>> {
-    // Lock mainloop to block pulseaudio from calling things during setup
+// Locks the mainloop, lets `register` wire up a pulseaudio introspection callback that reports
+// back over the channel it's handed, unlocks, then drains the channel into a `HashMap`. Shared
+// by `get_sources`/`get_sinks` so the list-fetch boilerplate only exists once.
+fn run_list_query<T>(
+    mainloop: &RMainloop,
+    register: impl FnOnce(mpsc::Sender<ListMsg<T>>),
+) -> Result<HashMap<u32, T>, Box<dyn Error>> {
     mainloop.borrow_mut().lock();
 
-    let introspector = context.borrow_mut().introspect();
+    let (tx, rx) = mpsc::channel();
+    register(tx);
 
-    let (src_tx, src_rx) = mpsc::channel();
-    introspector.get_source_info_list(move |src| match src {
-        ListResult::Error => {
-            let msg = "Failed to retrieve ListResult".into();
-            error!("{}", msg);
-            src_tx.send(SrcListState::Err(msg)).unwrap();
-        }
-        ListResult::End => {
-            src_tx.send(SrcListState::Done).unwrap();
-        }
-        ListResult::Item(item) => {
-            let source_name = match &item.name {
-                None => "unknown".to_string(),
-                Some(name) => name.to_string(),
-            };
+    mainloop.borrow_mut().unlock();
 
-            src_tx.send(
-                SrcListState::Item(
-                    item.index,
-                    SourceDatum::new(source_name, item.mute)
-                )
-            ).unwrap();
+    let mut items = HashMap::new();
+    loop {
+        match rx.recv()? {
+            ListMsg::Item(index, item) => {
+                items.insert(index, item);
+            }
+            ListMsg::Done => {
+                trace!("Retrieved list info");
+                return Ok(items);
+            }
+            ListMsg::Err(err) => {
+                error!("Caught error waiting: {}", err);
+                return Err(err.into());
+            }
         }
-    });
+    }
+}
 
-    let mut sources = HashMap::new();
+// Same idiom as `run_list_query`, but for a targeted single-index query: keeps only the last
+// `Item` seen before `Done`. Shared by `get_source_by_index`/`get_sink_by_index`.
+fn run_index_query<T>(
+    mainloop: &RMainloop,
+    register: impl FnOnce(mpsc::Sender<ListMsg<T>>),
+) -> Result<Option<T>, Box<dyn Error>> {
+    mainloop.borrow_mut().lock();
+
+    let (tx, rx) = mpsc::channel();
+    register(tx);
 
-    // Unlock mainloop to let pulseaudio call the above callback.
     mainloop.borrow_mut().unlock();
-    loop {
-        let item = src_rx.recv()?;
 
-        match item {
-            SrcListState::Item(index, source) => {
-                sources.insert(index, source);
+    let mut found = None;
+    loop {
+        match rx.recv()? {
+            ListMsg::Item(_, item) => {
+                found = Some(item);
             }
-            SrcListState::Done => {
-                trace!("Retrieved source info");
-                return Ok(sources);
+            ListMsg::Done => {
+                trace!("Retrieved index info");
+                return Ok(found);
             }
-            SrcListState::Err(err) => {
+            ListMsg::Err(err) => {
                 error!("Caught error waiting: {}", err);
-                return Err(err.to_owned().into());
+                return Err(err.into());
             }
         }
     }
 }
 
-#[derive(Debug, Clone)]
-enum DefaultSourceState {
-    NoDefault,
-    Default(String),
+fn get_sources(context: &RContext, mainloop: &RMainloop) -> Result<Sources, Box<dyn Error>> {
+    run_list_query(mainloop, |tx| {
+        context.borrow_mut().introspect().get_source_info_list(move |src| {
+            let msg = match src {
+                ListResult::Error => ListMsg::Err("Failed to retrieve ListResult".to_string()),
+                ListResult::End => ListMsg::Done,
+                ListResult::Item(item) => {
+                    let name = item.name.as_deref().unwrap_or("unknown").to_string();
+                    ListMsg::Item(
+                        item.index,
+                        SourceDatum::new(name, item.mute, item.volume, item.base_volume),
+                    )
+                }
+            };
+            tx.send(msg).unwrap();
+        });
+    })
 }
 
-fn find_default_source_name(
+// Targeted, single-index counterpart to `get_sources`, used to keep `ListenerState.sources`
+// updated incrementally instead of re-fetching the whole list on every subscribe event.
+fn get_source_by_index(
     context: &RContext,
     mainloop: &RMainloop,
-) -> Result<String, Box<dyn Error>> {
-    // Block pulseaudio from inboking callbacks
-    mainloop.borrow_mut().lock();
-
-    let introspector = context.borrow_mut().introspect();
-    let (src_tx, src_rx) = mpsc::channel();
+    idx: u32,
+) -> Result<Option<SourceDatum>, Box<dyn Error>> {
+    run_index_query(mainloop, |tx| {
+        context
+            .borrow_mut()
+            .introspect()
+            .get_source_info_by_index(idx, move |src| {
+                let msg = match src {
+                    ListResult::Error => ListMsg::Err("Failed to retrieve source info".to_string()),
+                    ListResult::End => ListMsg::Done,
+                    ListResult::Item(item) => {
+                        let name = item.name.as_deref().unwrap_or("unknown").to_string();
+                        ListMsg::Item(
+                            item.index,
+                            SourceDatum::new(name, item.mute, item.volume, item.base_volume),
+                        )
+                    }
+                };
+                tx.send(msg).unwrap();
+            });
+    })
+}
 
-    {
-        introspector.get_server_info(move |server_info| {
-            trace!("Server info: {:?}", server_info);
-            match &server_info.default_source_name {
-                None => {
-                    info!("no default source");
-                    src_tx.send(DefaultSourceState::NoDefault).unwrap()
-                }
-                Some(value) => {
-                    info!("Default source: '{:?}'", value);
-                    src_tx
-                        .send(DefaultSourceState::Default(value.to_string()))
-                        .unwrap();
+fn get_sinks(context: &RContext, mainloop: &RMainloop) -> Result<Sinks, Box<dyn Error>> {
+    run_list_query(mainloop, |tx| {
+        context.borrow_mut().introspect().get_sink_info_list(move |sink| {
+            let msg = match sink {
+                ListResult::Error => ListMsg::Err("Failed to retrieve ListResult".to_string()),
+                ListResult::End => ListMsg::Done,
+                ListResult::Item(item) => {
+                    let name = item.name.as_deref().unwrap_or("unknown").to_string();
+                    ListMsg::Item(item.index, SinkDatum::new(name, item.mute))
                 }
             };
+            tx.send(msg).unwrap();
         });
-    }
+    })
+}
+
+// Targeted, single-index counterpart to `get_sinks`, used to keep `ListenerState.sinks`
+// updated incrementally instead of re-fetching the whole list on every subscribe event.
+fn get_sink_by_index(
+    context: &RContext,
+    mainloop: &RMainloop,
+    idx: u32,
+) -> Result<Option<SinkDatum>, Box<dyn Error>> {
+    run_index_query(mainloop, |tx| {
+        context
+            .borrow_mut()
+            .introspect()
+            .get_sink_info_by_index(idx, move |sink| {
+                let msg = match sink {
+                    ListResult::Error => ListMsg::Err("Failed to retrieve sink info".to_string()),
+                    ListResult::End => ListMsg::Done,
+                    ListResult::Item(item) => {
+                        let name = item.name.as_deref().unwrap_or("unknown").to_string();
+                        ListMsg::Item(item.index, SinkDatum::new(name, item.mute))
+                    }
+                };
+                tx.send(msg).unwrap();
+            });
+    })
+}
+
+// Fetches a single field off of `ServerInfo` (the default source/sink name) via `select`,
+// shared by `get_default_source_index`/`get_default_sink_index` so only the field being read
+// differs between the two.
+fn fetch_default_name(
+    mainloop: &RMainloop,
+    context: &RContext,
+    select: impl Fn(&ServerInfo) -> Option<String> + 'static,
+) -> Result<Option<String>, Box<dyn Error>> {
+    mainloop.borrow_mut().lock();
+
+    let (tx, rx) = mpsc::channel();
+    context.borrow_mut().introspect().get_server_info(move |server_info| {
+        trace!("Server info: {:?}", server_info);
+        let _ = tx.send(select(server_info));
+    });
 
-    // Allow pulseaudio to process callbacks again
     mainloop.borrow_mut().unlock();
-    loop {
-        trace!("grabbing default source value");
-        let default_source = src_rx.recv()?;
-        trace!("Grabbed default source");
-        match default_source {
-            DefaultSourceState::NoDefault => {
-                return Ok("No default source".to_owned());
-            }
-            DefaultSourceState::Default(name) => {
-                trace!("Returning from get_sources");
-                return Ok(name.to_owned());
-            }
-        };
-    }
+    Ok(rx.recv()?)
 }
 
+// Resolves `name` against a cached `Sources`/`Sinks` map by comparing it to each entry's name
+// (via `name_of`). Returns `None` both when `name` is `None` (no default reported by the
+// server) and when no cached entry matches it yet.
+fn resolve_index_by_name<T>(
+    devices: &HashMap<u32, T>,
+    name: Option<&str>,
+    name_of: impl Fn(&T) -> &str,
+) -> Option<u32> {
+    let name = name?;
+    devices
+        .iter()
+        .find(|(_, device)| name_of(device) == name)
+        .map(|(index, _)| *index)
+}
+
+// Resolves the default source's index against an already-cached `Sources` map (no
+// `get_source_info_list` round-trip). Returns `Ok(None)` both when pulseaudio reports no
+// default source, and when the reported default isn't present in `sources` yet.
 fn get_default_source_index(
     mainloop: &RMainloop,
     context: &RContext,
     sources: &Sources,
-) -> Result<u32, Box<dyn Error>> {
-    let default_source_name = find_default_source_name(context, mainloop)?;
-
-    for (index, source) in sources {
-        if source.name == default_source_name {
-            debug!("Default source is: '{}', index: {}", source.name, index);
-            return Ok(*index);
-        }
+) -> Result<Option<u32>, Box<dyn Error>> {
+    let default_name = fetch_default_name(mainloop, context, |info| {
+        info.default_source_name.as_ref().map(|name| name.to_string())
+    })?;
+    let idx = resolve_index_by_name(sources, default_name.as_deref(), |source| &source.name);
+    match idx {
+        Some(index) => debug!("Default source is index {}", index),
+        None => debug!("No cached source matches default source name {:?}", default_name),
     }
+    Ok(idx)
+}
 
-    error!("failed to set default source");
-    Err("failed to set default source".into())
+// Resolves the default sink's index against an already-cached `Sinks` map (no
+// `get_sink_info_list` round-trip). Returns `Ok(None)` both when pulseaudio reports no
+// default sink, and when the reported default isn't present in `sinks` yet.
+fn get_default_sink_index(
+    mainloop: &RMainloop,
+    context: &RContext,
+    sinks: &Sinks,
+) -> Result<Option<u32>, Box<dyn Error>> {
+    let default_name = fetch_default_name(mainloop, context, |info| {
+        info.default_sink_name.as_ref().map(|name| name.to_string())
+    })?;
+    let idx = resolve_index_by_name(sinks, default_name.as_deref(), |sink| &sink.name);
+    match idx {
+        Some(index) => debug!("Default sink is index {}", index),
+        None => debug!("No cached sink matches default sink name {:?}", default_name),
+    }
+    Ok(idx)
 }
 
-fn setup_logs() {
-    let args = Args::parse();
+fn setup_logs(args: &Args) {
     let log_env = if args.verbose {
         Env::default().default_filter_or("debug")
     } else {
@@ -252,20 +510,70 @@ fn setup_logs() {
         .init();
 }
 
+// Message forwarded from the subscribe callback to the event loop. Carries enough detail
+// (facility, operation, and index) that the loop can apply a targeted update instead of
+// re-fetching everything.
+#[derive(Debug, Clone, Copy)]
+enum SourceEvent {
+    New(u32),
+    Changed(u32),
+    Removed(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SinkEvent {
+    New(u32),
+    Changed(u32),
+    Removed(u32),
+}
+
+enum StateChange {
+    Source(SourceEvent),
+    Sink(SinkEvent),
+    Server,
+    // A command from a control-socket client to run against PulseAudio (CLI subcommands call
+    // `run_command` directly instead); carries the id of the control-socket client the result should be
+    // reported back to, so the reply can be routed through `broadcast_events` (the one thread
+    // allowed to write to that client's stream) instead of written here directly.
+    Control(Command, u64),
+    // SIGINT/SIGTERM was received; unblock the event loop so it can tear down cleanly.
+    Shutdown,
+}
+
+// Bundles the handles the event loop in `subscribe_source_mute` needs beyond the PulseAudio
+// mainloop/context/state triple, so adding another one doesn't grow the function's argument
+// list.
+struct EventLoopCtx {
+    snapshot: Arc<Mutex<StateSnapshot>>,
+    socket_tx: mpsc::Sender<OutgoingMessage>,
+    tx: mpsc::Sender<StateChange>,
+    rx: mpsc::Receiver<StateChange>,
+    formatter: Box<dyn Formatter>,
+    control_socket_handle: control_socket::Handle,
+}
+
 fn subscribe_source_mute(
     mainloop: RMainloop,
     context: RContext,
     mut state: ListenerState,
+    ctx: EventLoopCtx,
 ) -> Result<(), Box<dyn Error>> {
-    // Sources toggle their mute state, default source changes Server state
-    let source_mask = InterestMaskSet::SOURCE | InterestMaskSet::SERVER;
+    let EventLoopCtx {
+        snapshot,
+        socket_tx,
+        tx,
+        rx,
+        formatter,
+        control_socket_handle,
+    } = ctx;
+    // Sources/sinks toggle their mute state, default source/sink changes Server state
+    let source_mask = InterestMaskSet::SOURCE | InterestMaskSet::SINK | InterestMaskSet::SERVER;
 
     trace!("Configuring context subscriber");
 
     // Block pulseaudio from invoking callbacks
     mainloop.borrow_mut().lock();
 
-    let (tx, rx) = mpsc::channel();
     // tell pulseaudio to notify us about Source & Server changes
     {
         // set callback that reacts to subscription changes
@@ -280,28 +588,24 @@ fn subscribe_source_mute(
 
                 match facility {
                     Facility::Source => {
-                        match operation {
-                            Operation::Changed => {
-                                // if state.default_source == id {
-                                // trace!("Default source changed config");
-                                // let old_mute_state = state.default_source().unwrap().mute;
-
-                                // tell callback that mainloop should update sources (can't do that here since
-                                // we're already inside a callback).
-                                // trace!("Source {} Changed", idx);
-                                tx.send(Facility::Source).unwrap();
-                            }
-                            Operation::New => {
-                                debug!("New source added with index {}", idx);
-                            }
-                            Operation::Removed => {
-                                debug!("Source with index {} removed", idx);
-                            }
-                        }
+                        let event = match operation {
+                            Operation::New => SourceEvent::New(idx),
+                            Operation::Changed => SourceEvent::Changed(idx),
+                            Operation::Removed => SourceEvent::Removed(idx),
+                        };
+                        tx.send(StateChange::Source(event)).unwrap();
+                    }
+                    Facility::Sink => {
+                        let event = match operation {
+                            Operation::New => SinkEvent::New(idx),
+                            Operation::Changed => SinkEvent::Changed(idx),
+                            Operation::Removed => SinkEvent::Removed(idx),
+                        };
+                        tx.send(StateChange::Sink(event)).unwrap();
                     }
                     Facility::Server => {
                         info!("Server change event");
-                        let _ = tx.send(Facility::Server);
+                        let _ = tx.send(StateChange::Server);
                     }
                     _ => debug!("Unrelated event: {:?}", facility),
                 }
@@ -319,7 +623,6 @@ fn subscribe_source_mute(
         );
     });
 
-    // TODO: We should also bind to shutdown signal for clean teardown here...
     trace!("Starting subscribe mainloop");
 
     // Allow pulseaudio to process callbacks again
@@ -328,44 +631,164 @@ fn subscribe_source_mute(
         // When we receive data via channel here, it means, we should update sources, and then
         // print if the mute state of the default source, changed.
 
-        let old_default_mute = {
-            match state.default_source() {
-                Some(src) => Some(src.mute),
-                None => None,
+        let old_default_source_idx = state.default_source;
+        let old_default_source_mute = state.default_source().map(|src| src.mute);
+        let old_default_source_volume_pct = state.default_source().map(|src| src.volume_pct());
+        let old_default_sink_idx = state.default_sink;
+        let old_default_sink_mute = state.default_sink().map(|sink| sink.mute);
+        trace!(
+            "current mute state: source {:?}, sink {:?}",
+            &old_default_source_mute, &old_default_sink_mute
+        );
+
+        let event = rx.recv()?;
+        match event {
+            StateChange::Shutdown => {
+                info!("Shutting down");
+                break;
+            }
+            StateChange::Server => {
+                handle_server_change(&mut state, &mainloop, &context)?;
+            }
+            StateChange::Source(SourceEvent::Removed(idx)) => {
+                debug!("Removing source {} from cache", idx);
+                state.sources.remove(&idx);
+                if state.default_source == Some(idx) {
+                    debug!("Removed source was the default source");
+                    state.default_source = None;
+                }
+            }
+            StateChange::Source(SourceEvent::New(idx)) | StateChange::Source(SourceEvent::Changed(idx)) => {
+                match get_source_by_index(&context, &mainloop, idx)? {
+                    Some(source) => {
+                        state.sources.insert(idx, source);
+                    }
+                    None => {
+                        // Query raced with removal; treat it the same as a Removed event.
+                        state.sources.remove(&idx);
+                        if state.default_source == Some(idx) {
+                            state.default_source = None;
+                        }
+                    }
+                }
             }
-        };
-        trace!("current source mute state: {:?}", &old_default_mute);
-
-        let event_type = rx.recv()?;
-        match event_type {
-            Facility::Server => {
-                let _ = handle_server_change(&mut state, &mainloop, &context);
-                // Always check source changes, to ensure the new default's mute state is compared
-                // against prior mute state.
-                state.sources = get_sources(&context, &mainloop).unwrap();
+            StateChange::Sink(SinkEvent::Removed(idx)) => {
+                debug!("Removing sink {} from cache", idx);
+                state.sinks.remove(&idx);
+                if state.default_sink == Some(idx) {
+                    debug!("Removed sink was the default sink");
+                    state.default_sink = None;
+                }
             }
-            Facility::Source => {
-                state.sources = get_sources(&context, &mainloop).unwrap();
+            StateChange::Sink(SinkEvent::New(idx)) | StateChange::Sink(SinkEvent::Changed(idx)) => {
+                match get_sink_by_index(&context, &mainloop, idx)? {
+                    Some(sink) => {
+                        state.sinks.insert(idx, sink);
+                    }
+                    None => {
+                        // Query raced with removal; treat it the same as a Removed event.
+                        state.sinks.remove(&idx);
+                        if state.default_sink == Some(idx) {
+                            state.default_sink = None;
+                        }
+                    }
+                }
             }
-            _ => {
-                panic!("impossible state");
+            StateChange::Control(command, client_id) => {
+                let result = run_command(command, &mainloop, &context, &state);
+                let response = match &result {
+                    Ok(()) => "{\"ok\":true}".to_string(),
+                    Err(err) => format!("{{\"ok\":false,\"error\":{:?}}}", err.to_string()),
+                };
+                let _ = socket_tx.send(OutgoingMessage::Reply { client_id, message: response });
+                if let Err(err) = result {
+                    error!("Control command failed: {}", err);
+                }
             }
         }
 
+        let current_snapshot = snapshot_from_state(&state);
+
         if let Some(new_src) = state.default_source() {
-            if Some(new_src.mute) != old_default_mute {
-                println!(
-                    "{}",
-                    match new_src.mute {
-                        true => "MUTED",
-                        false => "UNMUTED",
+            let idx = state.default_source.unwrap();
+            // The default source itself can change between the two `state.default_source()`
+            // reads above (e.g. a `Server` event switching to a different physical device), in
+            // which case `old_default_source_mute`/`old_default_source_volume_pct` describe the
+            // previous default, not this one. Treat that as a fresh default rather than diffing
+            // one device's state against another's.
+            let default_source_changed = old_default_source_idx != Some(idx);
+            if default_source_changed || Some(new_src.mute) != old_default_source_mute {
+                if let Some(line) = formatter.event(
+                    OutputEvent::SourceMute { idx, name: &new_src.name, muted: new_src.mute },
+                    &current_snapshot,
+                ) {
+                    println!("{}", line);
+                }
+                let _ = socket_tx.send(OutgoingMessage::Event(SocketEvent::Mute {
+                    source: new_src.name.clone(),
+                    muted: new_src.mute,
+                }));
+            } else if let Some(old_pct) = old_default_source_volume_pct {
+                let new_pct = new_src.volume_pct();
+                if (new_pct - old_pct).abs() >= VOLUME_CHANGE_THRESHOLD_PCT {
+                    if let Some(line) = formatter.event(
+                        OutputEvent::SourceVolume { idx, name: &new_src.name, pct: new_pct },
+                        &current_snapshot,
+                    ) {
+                        println!("{}", line);
                     }
-                );
+                    let _ = socket_tx.send(OutgoingMessage::Event(SocketEvent::Volume {
+                        source: new_src.name.clone(),
+                        pct: new_pct,
+                    }));
+                }
+            }
+        } else if old_default_source_mute.is_some() {
+            // Only fires on the Some -> None transition, not on every unrelated event while
+            // there's already no default source.
+            if let Some(line) = formatter.event(OutputEvent::NoDefaultSource, &current_snapshot) {
+                println!("{}", line);
+            }
+        }
+
+        if let Some(new_sink) = state.default_sink() {
+            let idx = state.default_sink.unwrap();
+            let default_sink_changed = old_default_sink_idx != Some(idx);
+            if default_sink_changed || Some(new_sink.mute) != old_default_sink_mute {
+                if let Some(line) = formatter.event(
+                    OutputEvent::SinkMute { idx, name: &new_sink.name, muted: new_sink.mute },
+                    &current_snapshot,
+                ) {
+                    println!("{}", line);
+                }
+                let _ = socket_tx.send(OutgoingMessage::Event(SocketEvent::SinkMute {
+                    sink: new_sink.name.clone(),
+                    muted: new_sink.mute,
+                }));
+            }
+        } else if old_default_sink_mute.is_some() {
+            // Only fires on the Some -> None transition, not on every unrelated event while
+            // there's already no default sink.
+            if let Some(line) = formatter.event(OutputEvent::NoDefaultSink, &current_snapshot) {
+                println!("{}", line);
             }
-        } else {
-            println!("No default source");
         }
+
+        *snapshot.lock().unwrap() = current_snapshot;
     }
+
+    // Ordered teardown: stop PulseAudio from invoking any more callbacks before dropping the
+    // connection, then stop the mainloop itself. `control_socket_handle` tears down the control
+    // socket when it drops, here or on any earlier `?` return from this function.
+    mainloop.borrow_mut().lock();
+    context.borrow_mut().set_subscribe_callback(None);
+    context.borrow_mut().set_state_callback(None);
+    context.borrow_mut().disconnect();
+    mainloop.borrow_mut().unlock();
+    mainloop.borrow_mut().stop();
+    drop(control_socket_handle);
+
+    Ok(())
 }
 
 fn handle_server_change(
@@ -373,16 +796,21 @@ fn handle_server_change(
     mainloop: &RMainloop,
     context: &RContext,
 ) -> Result<(), Box<dyn Error>> {
-    // Check if default source changed and update state
+    // Check if default source/sink changed and update state
     debug!("Updating default source after server config change");
-    // TODO: Do we need to check if the source map needs updating...?
-    // Ideally - we collect sources once on start, then use the source add/remove subscriptions to
-    // keep updated...
     state.default_source = get_default_source_index(mainloop, context, &state.sources)?;
 
     debug!(
-        "Default source is now: {}",
-        state.default_source().unwrap().name
+        "Default source is now: {:?}",
+        state.default_source().map(|src| &src.name)
+    );
+
+    debug!("Updating default sink after server config change");
+    state.default_sink = get_default_sink_index(mainloop, context, &state.sinks)?;
+
+    debug!(
+        "Default sink is now: {:?}",
+        state.default_sink().map(|sink| &sink.name)
     );
 
     Ok(())
@@ -0,0 +1,29 @@
+// Signal handling: installs SIGINT/SIGTERM handlers on a dedicated thread and forwards a
+// `StateChange::Shutdown` onto the same channel the subscribe event loop already consumes (and
+// that control-socket commands arrive on), so a single blocking `rx.recv()` is enough to
+// multiplex "something changed" and "time to quit" without a separate select loop.
+
+use std::error::Error;
+use std::sync::mpsc;
+use std::thread;
+
+use log::{debug, error};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+use crate::StateChange;
+
+pub fn spawn(tx: mpsc::Sender<StateChange>) -> Result<(), Box<dyn Error>> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            debug!("Received signal {}, shutting down", signal);
+            let _ = tx.send(StateChange::Shutdown);
+        } else {
+            error!("Signal iterator ended without delivering a signal");
+        }
+    });
+
+    Ok(())
+}